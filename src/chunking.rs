@@ -0,0 +1,67 @@
+// Content-defined chunking: splits a byte stream on rolling-hash boundaries so that
+// re-putting a slightly edited blob only touches the chunks that actually changed.
+// Uses a gear hash (a cheap rolling variant of buzhash): one 64-bit table lookup and
+// shift per byte, cut whenever the low bits of the hash are all zero.
+
+use sha2::{Digest, Sha256};
+
+pub const MIN_CHUNK_SIZE: usize = 2 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+// mask width chosen so P(cut) ~= 1/8192, giving an ~8KiB average chunk size
+const BOUNDARY_MASK: u64 = (1 << 13) - 1;
+
+pub struct Chunk {
+    pub id: String,
+    pub bytes: Vec<u8>
+}
+
+const fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    let mut seed = 0x5EED_u64;
+    while i < 256 {
+        seed = splitmix64(seed);
+        table[i] = seed;
+        i += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+// sha256, hex-encoded: unlike a 64-bit hash (FNV-1a, std's DefaultHasher, ...), this is
+// collision-resistant enough that two different chunks can be trusted never to land on the
+// same id and silently clobber each other in the content-addressed store.
+fn chunk_id(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hex::encode(hasher.finalize())
+}
+
+pub fn chunk_bytes(data: &[u8]) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    if data.is_empty() {
+        return chunks;
+    }
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    for i in 0..data.len() {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        let len = i - start + 1;
+        let at_boundary = len >= MIN_CHUNK_SIZE && hash & BOUNDARY_MASK == 0;
+        if at_boundary || len >= MAX_CHUNK_SIZE || i == data.len() - 1 {
+            let bytes = data[start..=i].to_vec();
+            chunks.push(Chunk { id: chunk_id(&bytes), bytes });
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    chunks
+}
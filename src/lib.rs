@@ -1,8 +1,18 @@
 mod utils;
 mod websocket_server;
+mod websocket_client;
+mod storage;
+mod chunking;
+mod pattern;
+mod user;
 use websocket_server::WebsocketServer;
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::time::SystemTime;
+use websocket_client::WebsocketClient;
+use storage::StorageAdapter;
+use pattern::segments_match;
+pub use user::User;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{SystemTime, Duration};
+use std::thread;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
@@ -22,7 +32,9 @@ pub enum GunValue {
     Number(f32),
     Text(String),
     Link(usize),
-    Children(BTreeMap<String, GunValue>)
+    Children(BTreeMap<String, GunValue>),
+    Bytes(Vec<u8>), // leaf payload of a single chunk
+    Blob(Vec<String>) // ordered chunk ids making up a chunked value
 }
 
 impl From<&str> for GunValue {
@@ -56,6 +68,35 @@ type Parents = Arc<RwLock<HashSet<(usize, String)>>>;
 type Subscriptions = Arc<RwLock<HashMap<usize, Callback>>>;
 type SharedNodeStore = Arc<RwLock<HashMap<usize, Node>>>;
 type NetworkAdapters = Arc<RwLock<HashMap<String, Box<dyn NetworkAdapter>>>>;
+type StorageAdapters = Arc<RwLock<HashMap<String, Box<dyn StorageAdapter>>>>;
+
+// A put whose stamp is ahead of our wall clock ("from the future"). Held here until the
+// clock catches up, then re-fed through apply_ham by the background drain thread.
+struct DeferredPut {
+    due_at: f64,
+    node: Node,
+    value: GunValue
+}
+type DeferredQueue = Arc<RwLock<Vec<DeferredPut>>>;
+
+// Bounded LRU set of recently-seen "#" message ids, so the gossip layer can drop
+// already-forwarded messages instead of rebroadcasting them forever.
+type SeenMessages = Arc<RwLock<(VecDeque<String>, HashSet<String>)>>;
+const SEEN_MESSAGES_MAX: usize = 10_000;
+
+// Content-addressed chunk store, shared across the whole graph so identical chunks from
+// different puts collapse to the same id instead of being stored (and sent) twice.
+type ChunkIndex = Arc<RwLock<BTreeMap<String, usize>>>;
+// TODO make configurable per Node / per put
+const CHUNKING_THRESHOLD: usize = 8 * 1024;
+
+// A standing glob subscription: fires `callback` for every currently-matching node, and
+// (via new_child) for every matching node created afterwards too.
+type PatternCallback = Arc<dyn Fn(GunValue, String)>;
+// Every (Node, subscription_id) pair a pattern subscription has installed via on/map on a
+// matching node, so off() can tear all of them back down again.
+type PatternInstallations = Arc<RwLock<Vec<(Node, usize)>>>;
+type PatternSubscriptions = Arc<RwLock<HashMap<usize, (Node, Vec<String>, PatternCallback, PatternInstallations)>>>;
 
 // TODO proper automatic tests
 // TODO break into submodules
@@ -73,7 +114,17 @@ pub struct Node {
     on_subscriptions: Subscriptions,
     map_subscriptions: Subscriptions,
     store: SharedNodeStore,
-    network_adapters: NetworkAdapters
+    network_adapters: NetworkAdapters,
+    storage_adapters: StorageAdapters,
+    deferred: DeferredQueue,
+    seen_messages: SeenMessages,
+    chunk_index: ChunkIndex,
+    pattern_subscriptions: PatternSubscriptions,
+    // Whether rehydrate_children_from_storage has already scanned this node's children in
+    // from storage. Distinct from children.is_empty() - plain traversal (get/new_child)
+    // creates stub children independent of persistence, so an empty-check alone would stop
+    // consulting storage the moment the first stub child appears.
+    children_rehydrated: Arc<RwLock<bool>>
 }
 
 impl Node {
@@ -89,19 +140,89 @@ impl Node {
             on_subscriptions: Subscriptions::default(),
             map_subscriptions: Subscriptions::default(),
             store: SharedNodeStore::default(),
-            network_adapters: NetworkAdapters::default()
+            network_adapters: NetworkAdapters::default(),
+            storage_adapters: StorageAdapters::default(),
+            deferred: DeferredQueue::default(),
+            seen_messages: SeenMessages::default(),
+            chunk_index: ChunkIndex::default(),
+            pattern_subscriptions: PatternSubscriptions::default(),
+            children_rehydrated: Arc::new(RwLock::new(false))
         };
         let mut server = WebsocketServer::new();
         let mut node_clone = node.clone();
         server.on_message(Box::new(move |msg: &SerdeJsonValue| {
-            node_clone.incoming_message(msg, false);
+            node_clone.incoming_message(msg, false, Some("ws_server"));
             println!("received from websocket: {}", msg);
         }));
         server.start();
         node.network_adapters.write().unwrap().insert("ws_server".to_string(), Box::new(server));
+        node.spawn_deferred_drain();
         node
     }
 
+    // Dials a peer and joins it into the relay mesh: messages received from it are
+    // gossiped on to every other adapter (minus loop protection via seen_messages), and
+    // messages originated locally or by other peers are forwarded to it in turn.
+    pub fn connect_to_peer(&mut self, url: &str) {
+        let mut client = WebsocketClient::new(url);
+        let mut node_clone = self.clone();
+        let origin = url.to_string();
+        client.on_message(Box::new(move |msg: &SerdeJsonValue| {
+            node_clone.incoming_message(msg, false, Some(&origin));
+        }));
+        client.start();
+        self.network_adapters.write().unwrap().insert(url.to_string(), Box::new(client));
+    }
+
+    // Background timer that drains future-dated puts once the wall clock reaches their
+    // stamp, re-feeding them through apply_ham so HAM state stays deterministic even when
+    // a peer's clock is briefly ahead of ours.
+    fn spawn_deferred_drain(&self) {
+        let deferred = self.deferred.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(50));
+            let now = Self::current_time();
+            let due: Vec<DeferredPut> = {
+                let mut queue = deferred.write().unwrap();
+                let (due, pending): (Vec<_>, Vec<_>) = queue.drain(..).partition(|d| d.due_at <= now);
+                *queue = pending;
+                due
+            };
+            for d in due {
+                d.node.apply_ham(d.value, d.due_at);
+            }
+        });
+    }
+
+    fn current_time() -> f64 {
+        (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos() as f64) / 1000.0
+    }
+
+    // Registers a durable backend that resident nodes fall back to on a cache miss and
+    // write through to on every put_local. Several can be registered; all are written,
+    // and the first one holding a path answers a lookup. The order in which they are
+    // consulted is not defined (they are kept in a HashMap).
+    pub fn add_storage_adapter(&self, name: &str, adapter: Box<dyn StorageAdapter>) {
+        self.storage_adapters.write().unwrap().insert(name.to_string(), adapter);
+    }
+
+    fn full_path(&self) -> String {
+        if self.path.is_empty() {
+            self.key.clone()
+        } else {
+            format!("{}/{}", self.path.join("/"), self.key)
+        }
+    }
+
+    fn load_from_storage(&self) -> Option<GunValue> {
+        for adapter in self.storage_adapters.read().unwrap().values() {
+            if let Some(value) = adapter.get(&self.full_path()) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
     fn new_child(&self, key: String) -> usize {
         assert!(key.len() > 0, "Key length must be greater than zero");
         let mut parents = HashSet::new();
@@ -115,23 +236,88 @@ impl Node {
             id,
             updated_at: Arc::new(RwLock::new(0.0)),
             key: key.clone(),
-            path,
+            path: path.clone(),
             value: Value::default(),
             children: Children::default(),
             parents: Arc::new(RwLock::new(parents)),
             on_subscriptions: Subscriptions::default(),
             map_subscriptions: Subscriptions::default(),
             store: self.store.clone(),
-            network_adapters: self.network_adapters.clone()
+            network_adapters: self.network_adapters.clone(),
+            storage_adapters: self.storage_adapters.clone(),
+            deferred: self.deferred.clone(),
+            seen_messages: self.seen_messages.clone(),
+            chunk_index: self.chunk_index.clone(),
+            pattern_subscriptions: self.pattern_subscriptions.clone(),
+            children_rehydrated: Arc::new(RwLock::new(false))
         };
+        let node_clone = node.clone();
         self.store.write().unwrap().insert(id, node);
-        self.children.write().unwrap().insert(key, id);
+        self.children.write().unwrap().insert(key.clone(), id);
+        self.notify_pattern_subscriptions(&node_clone, &path, &key);
         id
     }
 
+    fn path_string(path: &[String], key: &str) -> String {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", path.join("/"), key)
+        }
+    }
+
+    // Lets a newly-created node pick up any standing subscribe_pattern registered on one
+    // of its ancestors, so a pattern keeps matching nodes that appear after it was set up.
+    fn notify_pattern_subscriptions(&self, node: &Node, parent_path: &[String], key: &str) {
+        let full_path = Self::path_string(parent_path, key);
+        for (base, segments, callback, installations) in self.pattern_subscriptions.read().unwrap().values() {
+            let base_path = base.full_path();
+            let relative = if base_path.is_empty() {
+                Some(full_path.clone())
+            } else if full_path == base_path {
+                Some(String::new())
+            } else {
+                full_path.strip_prefix(&format!("{}/", base_path)).map(|s| s.to_string())
+            };
+            let relative = match relative {
+                Some(r) => r,
+                None => continue
+            };
+            let relative_segments: Vec<String> = if relative.is_empty() {
+                Vec::new()
+            } else {
+                relative.split('/').map(|s| s.to_string()).collect()
+            };
+            if segments_match(segments, &relative_segments) {
+                Self::install_pattern_callback(node, callback, installations);
+            }
+        }
+    }
+
+    // Installs both on() and map() on a node that just started matching a pattern, so a
+    // pattern subscriber sees the node's own value changes as well as its children's -
+    // the same two ways a caller would observe a node reached directly instead of via
+    // subscribe_pattern. Both installed subscription ids are recorded so off() can tear
+    // them back down.
+    fn install_pattern_callback(node: &Node, callback: &PatternCallback, installations: &PatternInstallations) {
+        let mut node_clone = node.clone();
+        let on_callback = callback.clone();
+        let on_id = node_clone.on(Box::new(move |v, k| on_callback(v, k)));
+        let map_callback = callback.clone();
+        let map_id = node_clone.map(Box::new(move |v, k| map_callback(v, k)));
+        installations.write().unwrap().push((node_clone.clone(), on_id));
+        installations.write().unwrap().push((node_clone, map_id));
+    }
+
     pub fn off(&mut self, subscription_id: usize) {
         self.on_subscriptions.write().unwrap().remove(&subscription_id);
         self.map_subscriptions.write().unwrap().remove(&subscription_id);
+        let removed = self.pattern_subscriptions.write().unwrap().remove(&subscription_id);
+        if let Some((_, _, _, installations)) = removed {
+            for (mut node, id) in installations.write().unwrap().drain(..) {
+                node.off(id);
+            }
+        }
     }
 
     pub fn on(&mut self, callback: Callback) -> usize {
@@ -140,7 +326,7 @@ impl Node {
         self.on_subscriptions.write().unwrap().insert(subscription_id, callback);
         let m = self.create_get_msg();
         if self.network_adapters.read().unwrap().len() > 0 {
-            self.ws_send(&m.to_string());
+            self.ws_send(&m.to_string(), None);
         }
         subscription_id
     }
@@ -152,7 +338,39 @@ impl Node {
         node
     }
 
+    // Standing query over a glob like "users/*/profile" or "chat/**/msg": installs both
+    // `on` and `map` on every currently-matching descendant (via new_child, also on every
+    // matching node that appears afterwards), relative to this node. Returns an id that
+    // off() can use to tear the whole subscription - and everything it installed - back
+    // down again.
+    pub fn subscribe_pattern(&self, pattern: &str, callback: Callback) -> usize {
+        let segments: Vec<String> = pattern.split('/').map(|s| s.to_string()).collect();
+        let shared_callback: PatternCallback = Arc::new(move |v, k| callback(v, k));
+        let subscription_id = get_id();
+        let installations = PatternInstallations::default();
+        self.pattern_subscriptions.write().unwrap().insert(
+            subscription_id,
+            (self.clone(), segments.clone(), shared_callback.clone(), installations.clone())
+        );
+        self.walk_pattern(&segments, Vec::new(), &shared_callback, &installations);
+        subscription_id
+    }
+
+    fn walk_pattern(&self, segments: &[String], relative_path: Vec<String>, callback: &PatternCallback, installations: &PatternInstallations) {
+        if segments_match(segments, &relative_path) {
+            Self::install_pattern_callback(self, callback, installations);
+        }
+        for (key, child_id) in self.children.read().unwrap().iter() {
+            if let Some(child) = self.store.read().unwrap().get(child_id) {
+                let mut next_path = relative_path.clone();
+                next_path.push(key.clone());
+                child.walk_pattern(segments, next_path, callback, installations);
+            }
+        }
+    }
+
     pub fn map(&self, callback: Callback) -> usize {
+        self.rehydrate_children_from_storage();
         for (key, child_id) in self.children.read().unwrap().iter() { // TODO can be faster with rayon multithreading?
             if let Some(child) = self.store.read().unwrap().get(&child_id) {
                 child.clone()._call_if_value_exists(&callback, key);
@@ -167,6 +385,7 @@ impl Node {
         if self.value.read().unwrap().is_some() {
             self.new_child(key)
         } else {
+            self.rehydrate_children_from_storage();
             let existing_id = match self.children.read().unwrap().get(&key) {
                 Some(node_id) => Some(*node_id),
                 _ => None
@@ -200,7 +419,7 @@ impl Node {
         }
     }
 
-    fn create_put_msg(&self, value: &GunValue, updated_at: f64) -> String {
+    fn create_put_msg(&self, value: &GunValue, updated_at: f64, signature_hex: Option<&str>) -> String {
         let msg_id = random_string(8);
         let full_path = &self.path.join("/");
         let key = &self.key.clone();
@@ -218,6 +437,9 @@ impl Node {
             },
             "#": msg_id,
         });
+        if let Some(signature_hex) = signature_hex {
+            json["put"][full_path]["~"] = json!(signature_hex);
+        }
 
         let puts = &mut json["put"];
         // if it's a nested node, put its parents also
@@ -239,15 +461,23 @@ impl Node {
         json.to_string()
     }
 
-    fn incoming_message(&mut self, msg: &SerdeJsonValue, is_from_array: bool) {
+    // `from` is the name of the adapter the message arrived on (None for locally
+    // originated messages), so ws_send can gossip it on to every *other* peer without
+    // bouncing it straight back to where it came from.
+    fn incoming_message(&mut self, msg: &SerdeJsonValue, is_from_array: bool, from: Option<&str>) {
         if let Some(array) = msg.as_array() {
             if is_from_array { return; } // don't allow array inside array
             for msg in array.iter() {
-                self.incoming_message(msg, true);
+                self.incoming_message(msg, true, from);
             }
             return;
         }
         if let Some(obj) = msg.as_object() {
+            if let Some(msg_id) = obj.get("#").and_then(|v| v.as_str()) {
+                if !self.mark_seen(msg_id) {
+                    return; // already seen - drop to avoid rebroadcast loops
+                }
+            }
             if let Some(put) = obj.get("put") {
                 if let Some(obj) = put.as_object() {
                     self.incoming_put(obj);
@@ -258,7 +488,31 @@ impl Node {
                     self.incoming_get(obj);
                 }
             }
+            if from.is_some() {
+                self.ws_send(&msg.to_string(), from);
+            }
+        }
+    }
+
+    fn mark_seen(&self, msg_id: &str) -> bool {
+        let mut seen = self.seen_messages.write().unwrap();
+        if seen.1.contains(msg_id) {
+            // refresh recency so an id that keeps arriving is the last to be evicted
+            if let Some(pos) = seen.0.iter().position(|id| id == msg_id) {
+                if let Some(id) = seen.0.remove(pos) {
+                    seen.0.push_back(id);
+                }
+            }
+            return false;
+        }
+        if seen.0.len() >= SEEN_MESSAGES_MAX {
+            if let Some(oldest) = seen.0.pop_front() {
+                seen.1.remove(&oldest);
+            }
         }
+        seen.0.push_back(msg_id.to_string());
+        seen.1.insert(msg_id.to_string());
+        true
     }
 
     fn incoming_put(&mut self, put: &serde_json::Map<String, SerdeJsonValue>) {
@@ -270,20 +524,59 @@ impl Node {
             if let Some(updated_at_times) = update_data["_"][">"].as_object() {
                 for (child_key, incoming_val_updated_at) in updated_at_times.iter() {
                     let incoming_val_updated_at = incoming_val_updated_at.as_f64().unwrap();
-                    let mut child = node.get(child_key);
-                    if *child.updated_at.read().unwrap() < incoming_val_updated_at {
-                        // TODO if incoming_val_updated_at > current_time { defer_operation() }
-                        if let Some(new_value) = update_data.get(child_key) {
-                            if let Ok(new_value) = serde_json::from_value::<GunValue>(new_value.clone()) {
-                                child.put_local(new_value, incoming_val_updated_at);
+                    let child = node.get(child_key);
+                    if let Some(new_value) = update_data.get(child_key) {
+                        if let Ok(new_value) = serde_json::from_value::<GunValue>(new_value.clone()) {
+                            if let Some(public_key) = Self::signed_namespace_key(updated_key) {
+                                let tuple = Self::signing_tuple(updated_key, child_key, &new_value, incoming_val_updated_at);
+                                let verified = update_data.get("~")
+                                    .and_then(|s| s.as_str())
+                                    .map(|sig| user::verify(public_key, tuple.as_bytes(), sig))
+                                    .unwrap_or(false);
+                                if !verified {
+                                    continue; // unsigned or forged write to a signed namespace - drop before HAM ever sees it
+                                }
                             }
+                            child.apply_ham(new_value, incoming_val_updated_at);
                         }
-                    } // TODO else append to history
+                    }
                 }
             }
         }
     }
 
+    // Gun's Hypothetical Amnesia Machine: given the local clock M, the incoming stamp S
+    // and the currently stored stamp C, decide deterministically (the same way on every
+    // peer) whether the incoming value should win.
+    fn apply_ham(&self, incoming_value: GunValue, stamp: f64) {
+        let now = Self::current_time();
+        if stamp > now {
+            // from the future - hold it until our clock reaches S, then re-run this check
+            self.deferred.write().unwrap().push(DeferredPut {
+                due_at: stamp,
+                node: self.clone(),
+                value: incoming_value
+            });
+            return;
+        }
+        let current = *self.updated_at.read().unwrap();
+        if stamp < current {
+            return; // historical - drop (TODO: append to per-node history ring if enabled)
+        }
+        if stamp == current {
+            // tie-break deterministically: lexically greater canonical serialization wins
+            let incoming_json = serde_json::to_string(&incoming_value).unwrap_or_default();
+            let current_json = match &*self.value.read().unwrap() {
+                Some(value) => serde_json::to_string(value).unwrap_or_default(),
+                None => String::new()
+            };
+            if incoming_json <= current_json {
+                return;
+            }
+        }
+        self.clone().put_local(incoming_value, stamp);
+    }
+
     fn _children_to_gun_value(&self, children: &BTreeMap<String, usize>) -> GunValue {
         let mut map = BTreeMap::<String, GunValue>::new();
         for (key, child_id) in children.iter() { // TODO faster with rayon?
@@ -309,8 +602,11 @@ impl Node {
         }
     }
 
-    fn ws_send(&self, msg: &String) {
-        for ws in self.network_adapters.read().unwrap().values() {
+    fn ws_send(&self, msg: &String, exclude: Option<&str>) {
+        for (name, ws) in self.network_adapters.read().unwrap().iter() {
+            if exclude == Some(name.as_str()) {
+                continue;
+            }
             ws.send_str(&msg);
             println!("sent: {}", msg);
             /*
@@ -322,18 +618,69 @@ impl Node {
         }
     }
 
+    // Repopulates the children map from the durable store's `scan(prefix)` when this
+    // node hasn't had its children scanned in from storage yet, so a relay that restarted
+    // with an empty HashMap::new() still finds children that were only ever written to
+    // disk. Gated on children_rehydrated rather than children.is_empty(): plain traversal
+    // (get/new_child) creates stub children independent of persistence, so by the time any
+    // of this node's storage-resident children were ever scanned in, children may already
+    // be non-empty. Only the immediate child segment is registered per scanned entry; any
+    // value below that is left to be lazily loaded the same way on its own node.
+    fn rehydrate_children_from_storage(&self) {
+        {
+            let mut rehydrated = self.children_rehydrated.write().unwrap();
+            if *rehydrated {
+                return;
+            }
+            *rehydrated = true;
+        }
+        let prefix = format!("{}/", self.full_path());
+        let mut scanned: Vec<(String, GunValue)> = Vec::new();
+        for adapter in self.storage_adapters.read().unwrap().values() {
+            for entry in adapter.scan(&prefix) {
+                scanned.push(entry);
+            }
+        }
+        for (path, value) in scanned {
+            let rest = match path.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest,
+                None => continue
+            };
+            let segment = match rest.split('/').next() {
+                Some(segment) if !segment.is_empty() => segment,
+                _ => continue
+            };
+            if self.children.read().unwrap().contains_key(segment) {
+                continue;
+            }
+            let id = self.new_child(segment.to_string());
+            if segment == rest {
+                if let Some(child) = self.store.read().unwrap().get(&id) {
+                    *child.value.write().unwrap() = Some(value);
+                }
+            }
+        }
+    }
+
     fn get_gun_value(&self) -> Option<GunValue> {
         let value = self.value.read().unwrap();
         if value.is_some() {
-            value.clone()
-        } else {
-            let children = self.children.read().unwrap();
-            if !children.is_empty() {
-                let obj = self._children_to_gun_value(&children);
-                return Some(obj)
-            }
-            None
+            return value.clone();
+        }
+        drop(value);
+        self.rehydrate_children_from_storage();
+        let children = self.children.read().unwrap();
+        if !children.is_empty() {
+            let obj = self._children_to_gun_value(&children);
+            return Some(obj);
         }
+        drop(children);
+        // not resident in memory - fall back to the durable store and lazily cache the hit
+        if let Some(value) = self.load_from_storage() {
+            *self.value.write().unwrap() = Some(value.clone());
+            return Some(value);
+        }
+        None
     }
 
     fn send_get_response_if_have(&self) {
@@ -355,7 +702,7 @@ impl Node {
                 },
                 "#": msg_id,
             }).to_string();
-            self.ws_send(&json);
+            self.ws_send(&json, None);
         }
     }
 
@@ -381,11 +728,127 @@ impl Node {
     }
 
     pub fn put(&mut self, value: GunValue) {
-        let time: f64 = (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos() as f64) / 1000.0;
+        let time: f64 = Self::current_time();
+        let value = self.chunk_if_needed(value);
         self.put_local(value.clone(), time);
         if self.network_adapters.read().unwrap().len() > 0 {
-            let m = self.create_put_msg(&value, time);
-            self.ws_send(&m);
+            let m = self.create_put_msg(&value, time, None);
+            self.ws_send(&m, None);
+        }
+    }
+
+    // Gets the node at the user-space root owned by `public_key_hex`, e.g. get_user(pk)
+    // is equivalent to get("~<pk>"). Writes under it should go through put_signed.
+    pub fn get_user(&mut self, public_key_hex: &str) -> Node {
+        self.get(&format!("~{}", public_key_hex))
+    }
+
+    // Like put(), but attaches a signature over (path, key, value, updated_at) so relays
+    // can forward the write without being able to forge it. incoming_put verifies this
+    // signature against the path's public key before the value ever reaches HAM merge.
+    pub fn put_signed(&mut self, value: GunValue, user: &user::User) {
+        let time: f64 = Self::current_time();
+        let value = self.chunk_if_needed(value);
+        let parent_path = self.path.join("/");
+        let signature = user.sign(Self::signing_tuple(&parent_path, &self.key, &value, time).as_bytes());
+        self.put_local(value.clone(), time);
+        if self.network_adapters.read().unwrap().len() > 0 {
+            let m = self.create_put_msg(&value, time, Some(&hex::encode(signature.to_bytes())));
+            self.ws_send(&m, None);
+        }
+    }
+
+    fn signing_tuple(path: &str, key: &str, value: &GunValue, updated_at: f64) -> String {
+        format!("{}\0{}\0{}\0{}", path, key, serde_json::to_string(value).unwrap_or_default(), updated_at)
+    }
+
+    // A node lives in a signed, user-owned namespace when its path's first segment is
+    // "~<public key hex>", mirroring Gun SEA's convention. "~chunks" is carved out here
+    // even though it also starts with "~": it's an internal, unsigned bookkeeping path
+    // (see store_chunk) that every node gossips on regardless of who authored the value
+    // it chunks, not a user-owned namespace.
+    fn signed_namespace_key(path: &str) -> Option<&str> {
+        let first = path.split('/').next()?;
+        if first == "~chunks" {
+            return None;
+        }
+        first.strip_prefix('~')
+    }
+
+    // Text values over CHUNKING_THRESHOLD are split on content-defined boundaries and stored
+    // as deduplicated chunk nodes; the put itself carries only the ordered chunk ids.
+    // Values of other variants are passed through unchunked.
+    fn chunk_if_needed(&self, value: GunValue) -> GunValue {
+        if let GunValue::Text(text) = &value {
+            if text.len() > CHUNKING_THRESHOLD {
+                let chunks = chunking::chunk_bytes(text.as_bytes());
+                let ids: Vec<String> = chunks.iter().map(|c| c.id.clone()).collect();
+                for chunk in chunks {
+                    self.store_chunk(chunk);
+                }
+                return GunValue::Blob(ids);
+            }
+        }
+        value
+    }
+
+    // Puts the chunk through the normal put_local/ws_send path (instead of only inserting
+    // it into the local store) so the bytes themselves - not just the Blob(ids) reference
+    // - actually reach peers and get written through to storage. A peer that receives the
+    // chunked put's "~chunks" sub-put via the ordinary incoming_put/apply_ham path
+    // registers the chunk in its own chunk_index the same way (see the path.len() == 1
+    // check in put_local), so resolve_blob works on any node that has actually seen it.
+    fn store_chunk(&self, chunk: chunking::Chunk) {
+        if self.chunk_index.read().unwrap().contains_key(&chunk.id) {
+            return;
+        }
+        let id = get_id();
+        let mut node = Self {
+            id,
+            updated_at: Arc::new(RwLock::new(0.0)),
+            key: chunk.id.clone(),
+            path: vec!["~chunks".to_string()],
+            value: Value::default(),
+            children: Children::default(),
+            parents: Parents::default(),
+            on_subscriptions: Subscriptions::default(),
+            map_subscriptions: Subscriptions::default(),
+            store: self.store.clone(),
+            network_adapters: self.network_adapters.clone(),
+            storage_adapters: self.storage_adapters.clone(),
+            deferred: self.deferred.clone(),
+            seen_messages: self.seen_messages.clone(),
+            chunk_index: self.chunk_index.clone(),
+            pattern_subscriptions: self.pattern_subscriptions.clone(),
+            children_rehydrated: Arc::new(RwLock::new(false))
+        };
+        self.store.write().unwrap().insert(id, node.clone());
+        let time = Self::current_time();
+        let value = GunValue::Bytes(chunk.bytes);
+        node.put_local(value.clone(), time);
+        if node.network_adapters.read().unwrap().len() > 0 {
+            let m = node.create_put_msg(&value, time, None);
+            node.ws_send(&m, None);
+        }
+    }
+
+    // Reconstructs a chunked value by looking up and concatenating its chunks in order.
+    pub fn resolve_blob(&self, value: &GunValue) -> Option<Vec<u8>> {
+        if let GunValue::Blob(ids) = value {
+            let index = self.chunk_index.read().unwrap();
+            let store = self.store.read().unwrap();
+            let mut bytes = Vec::new();
+            for id in ids {
+                let node_id = index.get(id)?;
+                let node = store.get(node_id)?;
+                match &*node.value.read().unwrap() {
+                    Some(GunValue::Bytes(chunk_bytes)) => bytes.extend_from_slice(chunk_bytes),
+                    _ => return None
+                }
+            }
+            Some(bytes)
+        } else {
+            None
         }
     }
 
@@ -396,6 +859,16 @@ impl Node {
         *self.updated_at.write().unwrap() = time;
         *self.value.write().unwrap() = Some(value.clone());
         *self.children.write().unwrap() = BTreeMap::new();
+        for adapter in self.storage_adapters.read().unwrap().values() {
+            adapter.put(&self.full_path(), value.clone(), time);
+        }
+        // chunk nodes live at the well-known "~chunks/<id>" path regardless of whether
+        // they were created locally (store_chunk) or arrived over the wire as an
+        // ordinary nested put (incoming_put) - either way, register them so resolve_blob
+        // can find them by id.
+        if self.path.len() == 1 && self.path[0] == "~chunks" {
+            self.chunk_index.write().unwrap().insert(self.key.clone(), self.id);
+        }
         for callback in self.on_subscriptions.read().unwrap().values() { // rayon?
             callback(value.clone(), self.key.clone());
         }
@@ -418,8 +891,28 @@ impl Node {
 mod tests {
     use crate::Node;
     use crate::GunValue;
+    use crate::User;
+    use crate::storage::SledStorageAdapter;
+    use crate::{NetworkAdapter, NetworkAdapterCallback};
     use std::cell::RefCell;
+    use std::sync::{Arc, Mutex};
     use std::time::{Duration, Instant};
+    use serde_json::json;
+
+    // Records every message handed to send_str, so tests can assert on what the gossip
+    // layer actually forwards without needing a real socket.
+    struct RecordingAdapter {
+        sent: Arc<Mutex<Vec<String>>>
+    }
+
+    impl NetworkAdapter for RecordingAdapter {
+        fn on_message(&mut self, _callback: NetworkAdapterCallback) {}
+        fn start(&self) {}
+        fn stop(&self) {}
+        fn send_str(&self, m: &String) {
+            self.sent.lock().unwrap().push(m.clone());
+        }
+    }
 
     // TODO proper test
     // TODO benchmark
@@ -443,6 +936,260 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn get_and_map_fall_back_to_storage_after_a_cold_restart() {
+        let storage_path = format!("{}/gun-rs-test-{}", std::env::temp_dir().display(), crate::get_id());
+        let build_node = || {
+            let gun = Node::new();
+            let adapter = SledStorageAdapter::new(&storage_path).unwrap();
+            gun.add_storage_adapter("sled", Box::new(adapter));
+            gun
+        };
+
+        {
+            let mut gun = build_node();
+            let mut parent = gun.get("Earendil");
+            let mut child = parent.get("son");
+            child.put_local("Elrond".into(), 1000.0);
+            // the Node itself is never dropped (its ws_server callback holds a clone of it),
+            // so release the sled handle and its file lock explicitly before reopening
+            gun.storage_adapters.write().unwrap().clear();
+        }
+
+        // simulate a cold restart: a brand new Node, with an empty in-memory graph, pointed
+        // at the same sled db
+        let mut gun = build_node();
+        let mut parent = gun.get("Earendil");
+        match parent.get("son").get_gun_value() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "Elrond"),
+            _ => panic!("expected the value to be loaded back from storage")
+        }
+
+        // map() must also see the storage-resident child, not just an exact-path get()
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = seen.clone();
+        parent.map(Box::new(move |_, key| seen_clone.lock().unwrap().push(key)));
+        assert_eq!(*seen.lock().unwrap(), vec!["son".to_string()]);
+
+        std::fs::remove_dir_all(&storage_path).ok();
+    }
+
+    #[test]
+    fn ham_tie_break_prefers_lexically_greater_value() {
+        let mut gun = Node::new();
+        let mut node = gun.get("Celebrimbor");
+        node.put_local("aaa".into(), 1000.0);
+        node.apply_ham("zzz".into(), 1000.0); // same stamp, lexically greater - should win
+        match &*node.value.read().unwrap() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "zzz"),
+            _ => panic!("expected a text value")
+        }
+        node.apply_ham("aaa".into(), 1000.0); // same stamp, lexically smaller - must not win
+        match &*node.value.read().unwrap() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "zzz"),
+            _ => panic!("expected a text value")
+        }
+    }
+
+    #[test]
+    fn ham_ignores_historical_update_and_defers_future_one() {
+        let mut gun = Node::new();
+        let mut node = gun.get("Glorfindel");
+        node.put_local("current".into(), 1000.0);
+        node.apply_ham("stale".into(), 500.0); // older stamp - must be dropped
+        match &*node.value.read().unwrap() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "current"),
+            _ => panic!("expected a text value")
+        }
+
+        let due_at = Node::current_time() + 200_000.0; // ~200ms into the future
+        node.apply_ham("future".into(), due_at);
+        assert!(matches!(&*node.value.read().unwrap(), Some(GunValue::Text(s)) if s == "current"));
+        std::thread::sleep(Duration::from_millis(400));
+        match &*node.value.read().unwrap() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "future"),
+            _ => panic!("deferred put was never drained")
+        }
+    }
+
+    #[test]
+    fn incoming_put_verifies_signature_before_merging_into_a_signed_namespace() {
+        let mut gun = Node::new();
+        let user = User::create();
+        let pubkey = user.public_key_hex();
+        let namespace = format!("~{}", pubkey);
+        let mut node = gun.get_user(&pubkey).get("bio");
+
+        // no signature at all - must be dropped before it ever reaches HAM merge
+        let unsigned_msg = json!({
+            "put": {
+                &namespace: {
+                    "_": { "#": &namespace, ">": { "bio": 5000.0 } },
+                    "bio": "forged"
+                }
+            },
+            "#": "unsigned-msg"
+        });
+        gun.incoming_message(&unsigned_msg, false, None);
+        assert!(node.get_gun_value().is_none());
+
+        // well-formed but wrong signature - must also be dropped
+        let tampered_msg = json!({
+            "put": {
+                &namespace: {
+                    "_": { "#": &namespace, ">": { "bio": 5001.0 } },
+                    "bio": "forged",
+                    "~": "00".repeat(64)
+                }
+            },
+            "#": "tampered-msg"
+        });
+        gun.incoming_message(&tampered_msg, false, None);
+        assert!(node.get_gun_value().is_none());
+
+        // a write actually signed by the namespace's owner must be applied
+        node.put_signed("legit".into(), &user);
+        match node.get_gun_value() {
+            Some(GunValue::Text(s)) => assert_eq!(s, "legit"),
+            _ => panic!("expected a validly signed write to be applied")
+        }
+    }
+
+    #[test]
+    fn chunk_bytes_reassembles_to_the_original_data_with_collision_resistant_ids() {
+        let data = "x".repeat(3 * crate::chunking::MAX_CHUNK_SIZE);
+        let chunks = crate::chunking::chunk_bytes(data.as_bytes());
+        assert!(chunks.len() > 1, "expected more than one chunk for this input size");
+        let mut reassembled = Vec::new();
+        for chunk in &chunks {
+            assert!(chunk.bytes.len() <= crate::chunking::MAX_CHUNK_SIZE);
+            assert_eq!(chunk.id.len(), 64, "expected a hex-encoded sha256 id");
+            reassembled.extend_from_slice(&chunk.bytes);
+        }
+        assert_eq!(reassembled, data.as_bytes());
+    }
+
+    #[test]
+    fn store_chunk_dedupes_identical_ids_and_resolve_blob_reassembles_the_chunks() {
+        let gun = Node::new();
+        let chunk_a = crate::chunking::Chunk { id: "chunk-a".to_string(), bytes: b"hello ".to_vec() };
+        let chunk_b = crate::chunking::Chunk { id: "chunk-b".to_string(), bytes: b"world".to_vec() };
+        let chunk_a_again = crate::chunking::Chunk { id: "chunk-a".to_string(), bytes: b"should be ignored".to_vec() };
+
+        gun.store_chunk(chunk_a);
+        gun.store_chunk(chunk_b);
+        gun.store_chunk(chunk_a_again); // same id as chunk_a - must be a no-op, not overwrite
+
+        let blob = GunValue::Blob(vec!["chunk-a".to_string(), "chunk-b".to_string()]);
+        let resolved = gun.resolve_blob(&blob).expect("expected both chunks to resolve");
+        assert_eq!(resolved, b"hello world");
+    }
+
+    #[test]
+    fn incoming_chunk_put_is_accepted_without_a_signature_so_a_receiver_can_resolve_the_blob() {
+        let mut sender = Node::new();
+        // uniform bytes never hit a content-defined boundary, so only MAX_CHUNK_SIZE cuts
+        // split them: use enough data for several chunks
+        let len = 3 * crate::chunking::MAX_CHUNK_SIZE;
+        let value = GunValue::Text("x".repeat(len));
+        let chunked = sender.chunk_if_needed(value);
+        let ids = match &chunked {
+            GunValue::Blob(ids) => ids.clone(),
+            _ => panic!("expected chunking to kick in")
+        };
+        assert!(ids.len() > 1, "expected more than one chunk for this input size");
+
+        // feed each chunk's "~chunks" put message through an independent second node, the
+        // way a peer receives it over the gossip mesh - not via store_chunk directly
+        let mut receiver = Node::new();
+        for id in &ids {
+            let node_id = *sender.chunk_index.read().unwrap().get(id).unwrap();
+            let chunk_node = sender.store.read().unwrap().get(&node_id).unwrap().clone();
+            let bytes = match &*chunk_node.value.read().unwrap() {
+                Some(GunValue::Bytes(b)) => b.clone(),
+                _ => panic!("expected chunk bytes")
+            };
+            let msg = chunk_node.create_put_msg(&GunValue::Bytes(bytes), Node::current_time(), None);
+            let parsed: serde_json::Value = serde_json::from_str(&msg).unwrap();
+            receiver.incoming_message(&parsed, false, None);
+        }
+
+        let resolved = receiver.resolve_blob(&GunValue::Blob(ids)).expect("chunk bytes should resolve on the receiver");
+        assert_eq!(resolved.len(), len);
+    }
+
+    #[test]
+    fn segments_match_handles_single_and_recursive_wildcards() {
+        use crate::pattern::segments_match;
+        let single = vec!["users".to_string(), "*".to_string(), "profile".to_string()];
+        assert!(segments_match(&single, &["users".to_string(), "alice".to_string(), "profile".to_string()]));
+        assert!(!segments_match(&single, &["users".to_string(), "alice".to_string(), "bob".to_string(), "profile".to_string()]));
+
+        let recursive = vec!["chat".to_string(), "**".to_string(), "msg".to_string()];
+        assert!(segments_match(&recursive, &["chat".to_string(), "msg".to_string()]));
+        assert!(segments_match(&recursive, &["chat".to_string(), "room1".to_string(), "msg".to_string()]));
+        assert!(segments_match(&recursive, &["chat".to_string(), "room1".to_string(), "sub".to_string(), "msg".to_string()]));
+        assert!(!segments_match(&recursive, &["chat".to_string(), "room1".to_string()]));
+    }
+
+    #[test]
+    fn pattern_subscription_fires_for_existing_and_future_nodes_and_off_tears_it_down() {
+        let mut gun = Node::new();
+        let mut profile_a = gun.get("users").get("alice").get("profile");
+        profile_a.put_local("alice-profile".into(), 1000.0);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = seen.clone();
+        let sub_id = gun.get("users").subscribe_pattern("*/profile", Box::new(move |v, _key| {
+            if let GunValue::Text(s) = v {
+                seen_clone.lock().unwrap().push(s);
+            }
+        }));
+        assert_eq!(*seen.lock().unwrap(), vec!["alice-profile".to_string()]);
+
+        // a node created after the subscription was registered should also be picked up
+        let mut profile_b = gun.get("users").get("bob").get("profile");
+        profile_b.put_local("bob-profile".into(), 1000.0);
+        let mut got = seen.lock().unwrap().clone();
+        got.sort();
+        assert_eq!(got, vec!["alice-profile".to_string(), "bob-profile".to_string()]);
+
+        // off() must stop delivering further updates and not just leak the subscription
+        gun.off(sub_id);
+        profile_a.put_local("alice-profile-2".into(), 2000.0);
+        assert_eq!(seen.lock().unwrap().len(), 2, "no further callbacks should fire after off()");
+    }
+
+    #[test]
+    fn ws_send_forwards_to_every_adapter_except_the_excluded_origin() {
+        let gun = Node::new();
+        gun.network_adapters.write().unwrap().clear(); // drop the ws_server Node::new() installs so only our mocks are exercised
+        let peer_a_sent = Arc::new(Mutex::new(Vec::new()));
+        let peer_b_sent = Arc::new(Mutex::new(Vec::new()));
+        gun.network_adapters.write().unwrap().insert("peer_a".to_string(), Box::new(RecordingAdapter { sent: peer_a_sent.clone() }));
+        gun.network_adapters.write().unwrap().insert("peer_b".to_string(), Box::new(RecordingAdapter { sent: peer_b_sent.clone() }));
+
+        gun.ws_send(&"hello".to_string(), Some("peer_a"));
+
+        assert!(peer_a_sent.lock().unwrap().is_empty(), "the excluded origin must not get the message echoed back");
+        assert_eq!(*peer_b_sent.lock().unwrap(), vec!["hello".to_string()]);
+    }
+
+    #[test]
+    fn incoming_message_drops_already_seen_ids_instead_of_rebroadcasting_them_forever() {
+        let mut gun = Node::new();
+        gun.network_adapters.write().unwrap().clear(); // drop the ws_server Node::new() installs so only our mocks are exercised
+        let peer_b_sent = Arc::new(Mutex::new(Vec::new()));
+        gun.network_adapters.write().unwrap().insert("peer_a".to_string(), Box::new(RecordingAdapter { sent: Arc::new(Mutex::new(Vec::new())) }));
+        gun.network_adapters.write().unwrap().insert("peer_b".to_string(), Box::new(RecordingAdapter { sent: peer_b_sent.clone() }));
+
+        let msg = json!({ "get": { "#": "some-node" }, "#": "msg-1" });
+        gun.incoming_message(&msg, false, Some("peer_a"));
+        gun.incoming_message(&msg, false, Some("peer_a")); // duplicate "#" - must be dropped, not re-gossiped
+
+        assert_eq!(peer_b_sent.lock().unwrap().len(), 1, "a message already seen must only ever be forwarded once");
+    }
+
     //var i = 28000, j = i, s = +new Date; while(--i){ gun.get('a'+i).get('lol').put(i+'yo') } console.log(j / ((+new Date - s) / 1000), 'ops/sec');
 
     #[test]
@@ -0,0 +1,16 @@
+// Matches a glob pattern's path segments against an actual path's segments. `*` matches
+// exactly one segment, `**` matches zero or more segments (recursive descent).
+pub fn segments_match(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(p), _) if p == "**" => {
+            if pattern.len() == 1 {
+                return true;
+            }
+            (0..=path.len()).any(|n| segments_match(&pattern[1..], &path[n..]))
+        },
+        (Some(p), Some(s)) if p == "*" || p == s => segments_match(&pattern[1..], &path[1..]),
+        _ => false
+    }
+}
@@ -0,0 +1,55 @@
+use crate::GunValue;
+
+// Durable key-value backend that sits behind the in-memory graph, so a relay can hold a
+// graph bigger than RAM and survive restarts. Nodes fall back to this on a cache miss
+// (get_child_id/get_gun_value) and write through to it from put_local.
+pub trait StorageAdapter: Send + Sync {
+    fn get(&self, path: &str) -> Option<GunValue>;
+    fn put(&self, path: &str, value: GunValue, updated_at: f64);
+    fn scan(&self, prefix: &str) -> Box<dyn Iterator<Item = (String, GunValue)>>;
+}
+
+// Embedded sled-backed adapter, keyed by the node's full path string.
+pub struct SledStorageAdapter {
+    db: sled::Db,
+}
+
+impl SledStorageAdapter {
+    pub fn new(path: &str) -> sled::Result<Self> {
+        Ok(Self { db: sled::open(path)? })
+    }
+
+    fn updated_at_key(path: &str) -> String {
+        format!("{}\0t", path)
+    }
+}
+
+impl StorageAdapter for SledStorageAdapter {
+    fn get(&self, path: &str) -> Option<GunValue> {
+        let bytes = self.db.get(path).ok()??;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn put(&self, path: &str, value: GunValue, updated_at: f64) {
+        if let Ok(bytes) = serde_json::to_vec(&value) {
+            let _ = self.db.insert(path, bytes);
+            let _ = self.db.insert(Self::updated_at_key(path), updated_at.to_be_bytes().to_vec());
+        }
+    }
+
+    fn scan(&self, prefix: &str) -> Box<dyn Iterator<Item = (String, GunValue)>> {
+        let entries: Vec<(String, GunValue)> = self.db
+            .scan_prefix(prefix)
+            .filter_map(|entry| entry.ok())
+            .filter_map(|(key, bytes)| {
+                let key = String::from_utf8(key.to_vec()).ok()?;
+                if key.ends_with("\0t") {
+                    return None;
+                }
+                let value = serde_json::from_slice(&bytes).ok()?;
+                Some((key, value))
+            })
+            .collect();
+        Box::new(entries.into_iter())
+    }
+}
@@ -0,0 +1,43 @@
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use rand::rngs::OsRng;
+
+// Holds the keypair for a user-space namespace (Gun's "~pubkey" subtree). Applications
+// keep this around to sign their own writes; relays never see the private half.
+pub struct User {
+    keypair: Keypair
+}
+
+impl User {
+    pub fn create() -> Self {
+        let mut csprng = OsRng {};
+        Self { keypair: Keypair::generate(&mut csprng) }
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.keypair.public.as_bytes())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.keypair.sign(message)
+    }
+}
+
+pub fn verify(public_key_hex: &str, message: &[u8], signature_hex: &str) -> bool {
+    let public_key_bytes = match hex::decode(public_key_hex) {
+        Ok(bytes) => bytes,
+        Err(_) => return false
+    };
+    let public_key = match PublicKey::from_bytes(&public_key_bytes) {
+        Ok(pk) => pk,
+        Err(_) => return false
+    };
+    let signature_bytes = match hex::decode(signature_hex) {
+        Ok(bytes) => bytes,
+        Err(_) => return false
+    };
+    let signature = match Signature::from_bytes(&signature_bytes) {
+        Ok(sig) => sig,
+        Err(_) => return false
+    };
+    public_key.verify(message, &signature).is_ok()
+}
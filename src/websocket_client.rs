@@ -0,0 +1,87 @@
+use crate::{NetworkAdapter, NetworkAdapterCallback};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{connect, Message};
+use tungstenite::stream::MaybeTlsStream;
+use url::Url;
+
+// How long a single read_message() call is allowed to block before giving send_str a
+// chance to grab the socket mutex. Keeps outbound gossip to a quiet peer from stalling
+// indefinitely behind a blocking read.
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Outbound counterpart to WebsocketServer: dials a single peer URL so this node can join
+// an existing relay mesh instead of only accepting inbound connections.
+pub struct WebsocketClient {
+    url: String,
+    callback: Arc<Mutex<Option<NetworkAdapterCallback>>>,
+    socket: Arc<Mutex<Option<tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>>>>
+}
+
+impl WebsocketClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            callback: Arc::new(Mutex::new(None)),
+            socket: Arc::new(Mutex::new(None))
+        }
+    }
+}
+
+impl NetworkAdapter for WebsocketClient {
+    fn on_message(&mut self, callback: NetworkAdapterCallback) {
+        *self.callback.lock().unwrap() = Some(callback);
+    }
+
+    fn start(&self) {
+        let url = self.url.clone();
+        let callback = self.callback.clone();
+        let socket_handle = self.socket.clone();
+        thread::spawn(move || {
+            let parsed = match Url::parse(&url) {
+                Ok(u) => u,
+                Err(_) => return
+            };
+            let (socket, _) = match connect(parsed) {
+                Ok(s) => s,
+                Err(_) => return
+            };
+            if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+                let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            }
+            *socket_handle.lock().unwrap() = Some(socket);
+            loop {
+                // the lock is only ever held for a single read attempt; on a plain (non-TLS)
+                // stream that read is bounded by READ_TIMEOUT, so send_str never waits long
+                // to get the socket. No read timeout is set on TLS streams.
+                let msg = socket_handle.lock().unwrap().as_mut().unwrap().read_message();
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        if let Ok(value) = serde_json::from_str(&text) {
+                            if let Some(cb) = callback.lock().unwrap().as_mut() {
+                                cb(&value);
+                            }
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(tungstenite::Error::Io(ref e))
+                        if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {},
+                    Err(_) => break
+                }
+            }
+        });
+    }
+
+    fn stop(&self) {
+        if let Some(socket) = self.socket.lock().unwrap().as_mut() {
+            let _ = socket.close(None);
+        }
+    }
+
+    fn send_str(&self, m: &String) {
+        if let Some(socket) = self.socket.lock().unwrap().as_mut() {
+            let _ = socket.write_message(Message::Text(m.clone()));
+        }
+    }
+}